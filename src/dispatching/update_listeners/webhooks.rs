@@ -1,6 +1,32 @@
-use std::net::SocketAddr;
+//! Webhook support.
+//!
+//! Everything in this module ([`Options`], [`setup_webhook`], secret-token
+//! verification and the [`UpdateSender`]/[`manual_update_listener`] channel
+//! plumbing) is transport-agnostic and lives behind the base `webhooks`
+//! feature, so it's available without pulling in any particular HTTP stack.
+//! Implement [`WebhookServer`] to plug in your own (warp, hyper, a FaaS
+//! handler, ...) and get all of that for free; the `webhooks-axum` feature's
+//! [`axum`] is one such implementation and depends on `webhooks` rather than
+//! duplicating any of it.
 
-use crate::{requests::Requester, types::InputFile};
+#![cfg(feature = "webhooks")]
+
+use std::{
+    convert::Infallible,
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    dispatching::{
+        stop_token::AsyncStopToken,
+        update_listeners::{StatefulListener, UpdateListener},
+    },
+    requests::Requester,
+    types::{AllowedUpdate, InputFile, Update, WebhookInfo},
+};
 
 /// Options related to setting up webhooks.
 pub struct Options {
@@ -33,13 +59,79 @@ pub struct Options {
     ///
     /// Default - None.
     pub drop_pending_updates: Option<bool>,
+
+    /// A secret token to be sent in a header `X-Telegram-Bot-Api-Secret-Token`
+    /// in every webhook request.
+    ///
+    /// Telegram will echo this token back in every update it sends, which
+    /// lets [`webhooks-axum`] handler reject requests from anyone who isn't
+    /// Telegram, even if they learn the webhook url.
+    ///
+    /// Must be 1-256 characters, only `A-Z`, `a-z`, `0-9`, `_` and `-` are
+    /// allowed.
+    ///
+    /// Default - None.
+    ///
+    /// [`webhooks-axum`]: crate::dispatching::update_listeners::webhooks::axum
+    pub secret_token: Option<String>,
+
+    /// The maximum allowed number of simultaneous HTTPS connections to the
+    /// webhook for update delivery, 1-100.
+    ///
+    /// Lower values limit the load on the bot's server, higher values
+    /// increase throughput. Telegram defaults to 40 if unset.
+    ///
+    /// Default - None.
+    pub max_connections: Option<u8>,
+
+    /// A list of the update kinds you want the bot to receive. See
+    /// [`AllowedUpdate`] for a complete list of available update kinds.
+    ///
+    /// Specifying this can be used to save the bot's server bandwidth by not
+    /// sending updates it never handles, e.g. `edited_channel_post`.
+    ///
+    /// Default - None (all update kinds except `chat_member`).
+    pub allowed_updates: Option<Vec<AllowedUpdate>>,
+
+    /// A fixed IP address for Telegram to use instead of resolving the
+    /// [`url`][Options.url]'s host via DNS.
+    ///
+    /// Useful for pinning to a specific edge/load-balancer IP or bypassing
+    /// flaky DNS.
+    ///
+    /// Default - None (resolved via DNS).
+    pub ip_address: Option<IpAddr>,
+
+    /// Whether to query [`getWebhookInfo`] before calling [`setWebhook`] and
+    /// skip the (rate-limited) call when the webhook is already configured
+    /// the way we want it.
+    ///
+    /// Regardless of whether the call is skipped, `pending_update_count`,
+    /// `last_error_date` and `last_error_message` from the response are
+    /// logged, so operators can see why previous deliveries may have failed.
+    ///
+    /// Default - true.
+    ///
+    /// [`getWebhookInfo`]: https://core.telegram.org/bots/api#getwebhookinfo
+    /// [`setWebhook`]: https://core.telegram.org/bots/api#setwebhook
+    pub reconcile: bool,
 }
 
 impl Options {
     /// Construct a new webhook options, see [`Options.address`] and
     /// [`Options.url`] for details.
     pub fn new(address: SocketAddr, url: url::Url) -> Self {
-        Self { address, url, certificate: None, drop_pending_updates: None }
+        Self {
+            address,
+            url,
+            certificate: None,
+            drop_pending_updates: None,
+            secret_token: None,
+            max_connections: None,
+            allowed_updates: None,
+            ip_address: None,
+            reconcile: true,
+        }
     }
 
     /// Upload your public key certificate so that the root certificate in use
@@ -54,10 +146,279 @@ impl Options {
     pub fn drop_pending_updates(self) -> Self {
         Self { drop_pending_updates: Some(true), ..self }
     }
+
+    /// Sets the secret token to be checked on every webhook request.
+    ///
+    /// ## Panics
+    ///
+    /// If `secret_token` is not 1-256 characters of `A-Z`, `a-z`, `0-9`, `_`
+    /// and `-`.
+    pub fn secret_token(self, secret_token: String) -> Self {
+        assert!(
+            is_valid_secret_token(&secret_token),
+            "secret token must be 1-256 characters long and contain only `A-Z`, `a-z`, `0-9`, `_` \
+             and `-`"
+        );
+
+        Self { secret_token: Some(secret_token), ..self }
+    }
+
+    /// Same as [`Options::secret_token`], but generates a cryptographically
+    /// random token instead of accepting one from the caller.
+    ///
+    /// This means spoofing protection is enabled by default, without users
+    /// having to come up with and manage a secret themselves.
+    ///
+    /// Requires `rand` as a direct dependency (see Cargo.toml).
+    pub fn secret_token_random(self) -> Self {
+        use rand::Rng;
+
+        let token = rand::thread_rng()
+            .sample_iter(rand::distributions::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        Self { secret_token: Some(token), ..self }
+    }
+
+    /// Sets the maximum allowed number of simultaneous HTTPS connections to
+    /// the webhook, 1-100.
+    ///
+    /// ## Panics
+    ///
+    /// If `max_connections` is not in the `1..=100` range.
+    pub fn max_connections(self, max_connections: u8) -> Self {
+        assert!(
+            (1..=100).contains(&max_connections),
+            "max_connections must be in the 1-100 range"
+        );
+
+        Self { max_connections: Some(max_connections), ..self }
+    }
+
+    /// Sets the list of update kinds the bot is interested in receiving.
+    pub fn allowed_updates(self, allowed_updates: Vec<AllowedUpdate>) -> Self {
+        Self { allowed_updates: Some(allowed_updates), ..self }
+    }
+
+    /// Sets a fixed IP address for Telegram to connect to, instead of
+    /// resolving the webhook url's host via DNS.
+    pub fn ip_address(self, ip_address: IpAddr) -> Self {
+        Self { ip_address: Some(ip_address), ..self }
+    }
+
+    /// Sets whether to reconcile with [`getWebhookInfo`] before calling
+    /// [`setWebhook`]. See [`Options.reconcile`] for details.
+    ///
+    /// [`getWebhookInfo`]: https://core.telegram.org/bots/api#getwebhookinfo
+    /// [`setWebhook`]: https://core.telegram.org/bots/api#setwebhook
+    pub fn reconcile(self, reconcile: bool) -> Self {
+        Self { reconcile, ..self }
+    }
+}
+
+fn is_valid_secret_token(token: &str) -> bool {
+    (1..=256).contains(&token.len())
+        && token.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Fetches the current webhook status from Telegram.
+///
+/// This is the same information [`setup_webhook`] uses internally to decide
+/// whether `setWebhook` needs to be called again; expose it so users can
+/// inspect webhook health themselves (e.g. from a `/health` endpoint).
+pub async fn webhook_info<R: Requester>(bot: &R) -> Result<WebhookInfo, R::Err> {
+    use crate::requests::Request;
+
+    bot.get_webhook_info().send().await
+}
+
+/// Whether `info` already reflects what `options` wants, so that calling
+/// `setWebhook` again would be a no-op.
+fn up_to_date(info: &WebhookInfo, options: &Options) -> bool {
+    // Telegram never echoes the secret token back in `getWebhookInfo`, so
+    // there is no way to tell whether the one it has matches `options` --
+    // always re-send rather than risk silently leaving a stale (or missing)
+    // token in place. Same reasoning applies to `drop_pending_updates`,
+    // which is a one-shot action rather than state to reconcile against.
+    if options.secret_token.is_some() || options.drop_pending_updates.is_some() {
+        return false;
+    }
+
+    let max_connections_ok = match options.max_connections {
+        Some(wanted) => info.max_connections == Some(u32::from(wanted)),
+        None => true,
+    };
+    let ip_address_ok = match &options.ip_address {
+        Some(wanted) => info.ip_address.as_deref() == Some(wanted.to_string().as_str()),
+        None => true,
+    };
+
+    info.url == options.url.as_str()
+        && info.has_custom_certificate == options.certificate.is_some()
+        && info.allowed_updates == options.allowed_updates
+        && max_connections_ok
+        && ip_address_ok
+}
+
+/// A handle used to feed updates into a [`manual_update_listener`] from
+/// outside of the usual polling/server loop.
+#[derive(Clone)]
+pub struct UpdateSender(mpsc::UnboundedSender<Result<Update, Infallible>>);
+
+impl UpdateSender {
+    /// Feeds `update` into the paired [`UpdateListener`].
+    pub fn send(&self, update: Update) {
+        // The receiving end is held by the paired `UpdateListener`; if it was
+        // dropped there is simply nowhere to deliver the update to.
+        let _ = self.0.send(Ok(update));
+    }
+}
+
+/// Creates an [`UpdateListener`] that owns no socket and runs no server of
+/// its own; updates are fed into it manually through the returned
+/// [`UpdateSender`].
+///
+/// This is the building block for embedding teloxide in single-invocation
+/// environments (e.g. AWS Lambda) where the runtime, not this crate, owns
+/// the request/response cycle. See [`process_single_update`] for turning one
+/// raw webhook request into a call to [`UpdateSender::send`].
+pub fn manual_update_listener() -> (UpdateSender, impl UpdateListener<Err = Infallible>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (stop_token, _stop_flag) = AsyncStopToken::new_pair();
+
+    let listener = StatefulListener::new(
+        (rx, stop_token),
+        tuple_first_mut,
+        move |state: &mut (_, AsyncStopToken)| state.1.clone(),
+    );
+
+    (UpdateSender(tx), listener)
+}
+
+/// An error that can happen while [`process_single_update`]-ing one webhook
+/// request.
+#[derive(Debug)]
+pub enum ProcessUpdateError {
+    /// The request's `X-Telegram-Bot-Api-Secret-Token` header didn't match
+    /// [`Options.secret_token`].
+    Unauthorized,
+
+    /// The request body could not be parsed as an [`Update`].
+    BadRequest(serde_json::Error),
 }
 
+impl fmt::Display for ProcessUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized => f.write_str("secret token didn't match"),
+            Self::BadRequest(e) => write!(f, "invalid update: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProcessUpdateError {}
+
+/// Processes one Telegram webhook request without binding any socket.
+///
+/// Verifies the secret token (if [`Options.secret_token`] is set) via
+/// `header`, deserializes `body` as an [`Update`] and feeds it into `sender`
+/// (obtained from [`manual_update_listener`]) for anything that also
+/// dispatches updates the usual way.
+///
+/// `answer` is called with the verified [`Update`] before this function
+/// returns, so it can build and hand back the body of a Telegram API method
+/// call (e.g. a `sendMessage` payload) to embed in the HTTP response --
+/// Telegram answers that method for you instead of requiring a separate API
+/// call. Return `None` to just acknowledge the update with an empty JSON
+/// object.
+///
+/// This decouples receiving updates from owning a socket, so the crate can
+/// be embedded in FaaS handlers instead of only the long-running server
+/// loop used by e.g. [`axum`][self::axum].
+pub fn process_single_update(
+    options: &Options,
+    sender: &UpdateSender,
+    body: &[u8],
+    header: impl Fn(&str) -> Option<&str>,
+    answer: impl FnOnce(&Update) -> Option<Vec<u8>>,
+) -> Result<Vec<u8>, ProcessUpdateError> {
+    let header_value = header("X-Telegram-Bot-Api-Secret-Token");
+    if !secret_token_matches(options.secret_token.as_deref(), header_value) {
+        return Err(ProcessUpdateError::Unauthorized);
+    }
+
+    let update: Update = serde_json::from_slice(body).map_err(ProcessUpdateError::BadRequest)?;
+    let response = answer(&update).unwrap_or_else(|| b"{}".to_vec());
+
+    sender.send(update);
+
+    Ok(response)
+}
+
+/// Checks `header_value` (the request's
+/// `X-Telegram-Bot-Api-Secret-Token` header, if any was sent) against
+/// `expected` (usually [`Options.secret_token`]).
+///
+/// Returns `true` when there is nothing to check (`expected` is `None`) as
+/// well as on an actual match. Shared by every [`WebhookServer`]
+/// implementation so this comparison lives in one place.
+pub fn secret_token_matches(expected: Option<&str>, header_value: Option<&str>) -> bool {
+    match expected {
+        Some(expected) => header_value == Some(expected),
+        None => true,
+    }
+}
+
+/// A pluggable transport for serving webhooks.
+///
+/// Implement this trait to back webhook update delivery with your own HTTP
+/// stack (warp, hyper, a custom server, ...) while still getting
+/// `setWebhook` setup, certificate upload and reconciliation from [`webhook`]
+/// and secret-token verification from [`secret_token_matches`] for free. The
+/// `webhooks-axum` feature's
+/// [`axum`][self::axum::axum] is one such implementation, built on top of
+/// [`AxumServer`][self::axum::AxumServer].
+pub trait WebhookServer {
+    /// Whatever else the caller needs to actually serve requests with, e.g.
+    /// an `axum::Router` or a `warp::Filter`.
+    type Output;
+
+    /// Builds the transport-specific server piece that receives HTTP
+    /// requests, checks their secret token with [`secret_token_matches`] and
+    /// forwards parsed [`Update`]s into `sender`.
+    fn build(sender: UpdateSender, options: &Options) -> Self::Output;
+}
+
+/// Sets up the webhook (see [`setup_webhook`]) and builds a
+/// transport-specific server `S` that is wired to this crate's
+/// [`UpdateListener`].
+///
+/// This is the shared entry point every [`WebhookServer`] implementation,
+/// including `webhooks-axum`'s [`axum`][self::axum::axum], is built on top
+/// of.
+pub async fn webhook<R, S>(
+    bot: R,
+    mut options: Options,
+) -> Result<(impl UpdateListener<Err = Infallible>, S::Output), R::Err>
+where
+    R: Requester,
+    S: WebhookServer,
+{
+    setup_webhook(bot, &mut options).await?;
+
+    let (sender, listener) = manual_update_listener();
+    let output = S::build(sender, &options);
+
+    Ok((listener, output))
+}
+
+// `webhooks-axum` depends on `webhooks` in Cargo.toml (`webhooks-axum =
+// ["webhooks", "dep:axum", ...]`), so enabling it always enables `webhooks`
+// and everything above stays compiled in.
 #[cfg(feature = "webhooks-axum")]
-pub use self::axum::{axum, axum_no_setup, axum_to_router};
+pub use self::axum::{axum, axum_no_setup, axum_to_router, AxumServer};
 
 #[cfg(feature = "webhooks-axum")]
 mod axum;
@@ -69,11 +430,44 @@ where
     use crate::requests::Request;
     use teloxide_core::requests::HasPayload;
 
-    let &mut Options { ref url, ref mut certificate, drop_pending_updates, .. } = options;
+    if options.reconcile {
+        let info = webhook_info(&bot).await?;
+
+        if let Some(last_error_message) = &info.last_error_message {
+            log::warn!(
+                "last webhook delivery failed at {:?}: {last_error_message}",
+                info.last_error_date
+            );
+        }
+
+        if info.pending_update_count != 0 {
+            log::info!("{} updates are pending delivery", info.pending_update_count);
+        }
+
+        if up_to_date(&info, options) {
+            log::debug!("webhook is already set up as wanted, skipping setWebhook");
+            return Ok(());
+        }
+    }
+
+    let &mut Options {
+        ref url,
+        ref mut certificate,
+        drop_pending_updates,
+        ref secret_token,
+        max_connections,
+        ref allowed_updates,
+        ip_address,
+        ..
+    } = options;
 
     let mut req = bot.set_webhook(url.clone());
     req.payload_mut().certificate = certificate.take();
     req.payload_mut().drop_pending_updates = drop_pending_updates;
+    req.payload_mut().secret_token = secret_token.clone();
+    req.payload_mut().max_connections = max_connections;
+    req.payload_mut().allowed_updates = allowed_updates.clone();
+    req.payload_mut().ip_address = ip_address.map(|ip| ip.to_string());
 
     req.send().await?;
 
@@ -83,3 +477,122 @@ where
 fn tuple_first_mut<A, B>(tuple: &mut (A, B)) -> &mut A {
     &mut tuple.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_token_rejects_empty() {
+        assert!(!is_valid_secret_token(""));
+    }
+
+    #[test]
+    fn secret_token_rejects_too_long() {
+        assert!(!is_valid_secret_token(&"a".repeat(257)));
+    }
+
+    #[test]
+    fn secret_token_accepts_boundary_lengths() {
+        assert!(is_valid_secret_token("a"));
+        assert!(is_valid_secret_token(&"a".repeat(256)));
+    }
+
+    #[test]
+    fn secret_token_rejects_disallowed_characters() {
+        assert!(!is_valid_secret_token("not valid!"));
+        assert!(!is_valid_secret_token("also/not-valid"));
+    }
+
+    #[test]
+    fn secret_token_accepts_allowed_characters() {
+        assert!(is_valid_secret_token("Valid_token-123"));
+    }
+
+    #[test]
+    fn secret_token_matches_when_none_expected() {
+        assert!(secret_token_matches(None, None));
+        assert!(secret_token_matches(None, Some("whatever")));
+    }
+
+    #[test]
+    fn secret_token_matches_on_exact_match() {
+        assert!(secret_token_matches(Some("abc"), Some("abc")));
+    }
+
+    #[test]
+    fn secret_token_matches_rejects_missing_or_wrong_header() {
+        assert!(!secret_token_matches(Some("abc"), None));
+        assert!(!secret_token_matches(Some("abc"), Some("abd")));
+        assert!(!secret_token_matches(Some("abc"), Some("ABC")));
+    }
+
+    fn test_options() -> Options {
+        Options::new(
+            "127.0.0.1:8443".parse().unwrap(),
+            "https://example.com/webhook".parse().unwrap(),
+        )
+    }
+
+    fn webhook_info_for(options: &Options) -> WebhookInfo {
+        WebhookInfo {
+            url: options.url.to_string(),
+            has_custom_certificate: options.certificate.is_some(),
+            pending_update_count: 0,
+            ip_address: None,
+            last_error_date: None,
+            last_error_message: None,
+            last_synchronization_error_date: None,
+            max_connections: None,
+            allowed_updates: options.allowed_updates.clone(),
+        }
+    }
+
+    #[test]
+    fn up_to_date_matches_identical_state() {
+        let options = test_options();
+        let info = webhook_info_for(&options);
+
+        assert!(up_to_date(&info, &options));
+    }
+
+    #[test]
+    fn up_to_date_detects_max_connections_mismatch() {
+        let options = test_options().max_connections(50);
+        let mut info = webhook_info_for(&options);
+
+        info.max_connections = Some(40);
+        assert!(!up_to_date(&info, &options));
+
+        info.max_connections = Some(50);
+        assert!(up_to_date(&info, &options));
+    }
+
+    #[test]
+    fn up_to_date_detects_ip_address_mismatch() {
+        let options = test_options().ip_address("1.2.3.4".parse().unwrap());
+        let mut info = webhook_info_for(&options);
+
+        info.ip_address = Some("5.6.7.8".to_owned());
+        assert!(!up_to_date(&info, &options));
+
+        info.ip_address = Some("1.2.3.4".to_owned());
+        assert!(up_to_date(&info, &options));
+    }
+
+    #[test]
+    fn up_to_date_always_resends_when_secret_token_is_set() {
+        let options = test_options().secret_token("a-valid-token".to_owned());
+        let info = webhook_info_for(&options);
+
+        assert!(!up_to_date(&info, &options));
+    }
+
+    #[test]
+    fn up_to_date_always_resends_when_dropping_pending_updates() {
+        let options = test_options().drop_pending_updates();
+        let info = webhook_info_for(&options);
+
+        assert!(!up_to_date(&info, &options));
+    }
+}
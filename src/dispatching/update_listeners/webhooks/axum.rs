@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+
+use crate::{dispatching::update_listeners::UpdateListener, requests::Requester, types::Update};
+
+use super::{secret_token_matches, webhook, Options, UpdateSender, WebhookServer};
+
+/// Webhook implementation based on the [axum] framework.
+///
+/// Returns a running [`UpdateListener`] and an [axum::Router] that you can
+/// merge into your own router or serve on its own.
+///
+/// [axum]: https://github.com/tokio-rs/axum
+pub async fn axum<R>(
+    bot: R,
+    options: Options,
+) -> Result<(impl UpdateListener<Err = Infallible>, Router), R::Err>
+where
+    R: Requester + Send + 'static,
+{
+    webhook::<R, AxumServer>(bot, options).await
+}
+
+/// Like [`axum`], but without the `setWebhook` call.
+pub fn axum_no_setup(options: Options) -> (impl UpdateListener<Err = Infallible>, Router) {
+    let (sender, listener) = super::manual_update_listener();
+    let router = AxumServer::build(sender, &options);
+
+    (listener, router)
+}
+
+/// Builds the [axum::Router] that receives Telegram updates and feeds them
+/// into `sender`.
+pub fn axum_to_router(sender: UpdateSender, options: &Options) -> Router {
+    AxumServer::build(sender, options)
+}
+
+/// The [`webhooks-axum`] [`WebhookServer`] implementation.
+///
+/// [`webhooks-axum`]: self
+pub struct AxumServer;
+
+impl WebhookServer for AxumServer {
+    type Output = Router;
+
+    fn build(sender: UpdateSender, options: &Options) -> Router {
+        let path = options.url.path().to_owned();
+
+        Router::new()
+            .route(&path, post(webhook_handler))
+            .with_state(WebhookState { sender, secret_token: options.secret_token.clone() })
+    }
+}
+
+#[derive(Clone)]
+struct WebhookState {
+    sender: UpdateSender,
+    secret_token: Option<String>,
+}
+
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+async fn webhook_handler(
+    State(WebhookState { sender, secret_token }): State<WebhookState>,
+    headers: axum::http::HeaderMap,
+    Json(update): Json<Update>,
+) -> StatusCode {
+    let header_value = headers.get(SECRET_HEADER).and_then(|v| v.to_str().ok());
+
+    if !secret_token_matches(secret_token.as_deref(), header_value) {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    sender.send(update);
+
+    StatusCode::OK
+}